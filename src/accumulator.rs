@@ -1,12 +1,31 @@
 use crate::millis;
 
+// Nominal loop rate the gyro is sampled at (see `gyro::SAMPLE_PERIOD`), used
+// to seed the observed-rate filter before enough samples have been seen to
+// estimate it for real.
+const NOMINAL_RATE_HZ: f32 = 500.0;
+
+// Number of samples averaged together before the observed sample rate is
+// re-estimated and fed into the low-pass filter.
+const RATE_ESTIMATE_SAMPLES: u32 = 50;
+
+// Low-pass filter coefficient applied to each new observed-rate estimate.
+const RATE_FILTER_ALPHA: f32 = 0.02;
+
+// An interval is considered stalled (e.g. a missed SPI transaction) once it
+// runs this many times longer than the expected period at the current
+// filtered rate.
+const STALL_INTERVAL_MULTIPLIER: f32 = 3.0;
 
 pub struct AccumulatorF32 {
     accumulated: f32,
     samples: u32,
     last_value: f32,
-    last_time: u32,
+    last_time_us: u32,
     integrated_center: f32,
+    rate_hz: f32,
+    rate_window_samples: u32,
+    rate_window_start_us: u32,
 }
 
 impl AccumulatorF32 {
@@ -15,29 +34,61 @@ impl AccumulatorF32 {
     }
 
     pub fn with_default(default: f32) -> Self {
+        let now = millis::get_micros();
+
         AccumulatorF32 {
             accumulated: default,
             samples: 0,
             last_value: 0.0,
-            last_time: millis::get_millis(),
+            last_time_us: now,
             integrated_center: 0.0,
+            rate_hz: NOMINAL_RATE_HZ,
+            rate_window_samples: 0,
+            rate_window_start_us: now,
         }
     }
 
     /**
-     * Integrate the added data using the trapezoidal method
+     * Integrate the added data using the trapezoidal method, over a
+     * microsecond-resolution interval. Intervals much longer than the
+     * expected sample period (per `rate_hz`) are treated as a stalled loop
+     * iteration and dropped so they can't inject a large spike into the
+     * integral; the observed sample rate is re-estimated periodically and
+     * low-pass filtered to track `rate_hz`.
      */
     pub fn add_data(&mut self, value: f32) {
-        let time = millis::get_millis();
+        let time = millis::get_micros();
+        let delta_time_us = time.wrapping_sub(self.last_time_us);
+
+        let expected_period_us = 1_000_000.0 / self.rate_hz;
+        if delta_time_us as f32 > expected_period_us * STALL_INTERVAL_MULTIPLIER {
+            // Stalled interval (e.g. a missed SPI transaction) - don't let
+            // the gap corrupt the integral, just resynchronize.
+            self.last_value = value;
+            self.last_time_us = time;
+            self.rate_window_start_us = time;
+            self.rate_window_samples = 0;
+            return;
+        }
 
-        let delta_time_ms = time - self.last_time;
-        let area =
-            delta_time_ms as f32 * 1e-3 * (self.last_value + value) / 2.0 - self.integrated_center;
+        let area = delta_time_us as f32 * 1e-6 * (self.last_value + value) / 2.0
+            - self.integrated_center;
 
         self.accumulated += area;
         self.last_value = value;
-        self.last_time = time;
+        self.last_time_us = time;
         self.samples += 1;
+
+        self.rate_window_samples += 1;
+        if self.rate_window_samples >= RATE_ESTIMATE_SAMPLES {
+            let elapsed_s = time.wrapping_sub(self.rate_window_start_us) as f32 * 1e-6;
+            if elapsed_s > 0.0 {
+                let observed_hz = self.rate_window_samples as f32 / elapsed_s;
+                self.rate_hz += (observed_hz - self.rate_hz) * RATE_FILTER_ALPHA;
+            }
+            self.rate_window_samples = 0;
+            self.rate_window_start_us = time;
+        }
     }
 
     pub fn get_integrated_value(&self) -> f32 {
@@ -51,7 +102,7 @@ impl AccumulatorF32 {
     pub fn reset(&mut self) {
         self.accumulated = 0.0;
         self.last_value = 0.0;
-        self.last_time = millis::get_millis();
+        self.last_time_us = millis::get_micros();
     }
 
     pub fn set_integrated_center(&mut self, center: f32) {
@@ -61,4 +112,14 @@ impl AccumulatorF32 {
     pub fn get_integrated_average(&self) -> f32 {
         self.accumulated / self.samples as f32
     }
+
+    /**
+     * Filtered sample rate observed from actual timestamps, in Hz. Tracks
+     * the true loop rate rather than the nominal value so stall detection
+     * stays accurate even if the loop runs consistently faster or slower
+     * than `NOMINAL_RATE_HZ`.
+     */
+    pub fn get_effective_rate_hz(&self) -> f32 {
+        self.rate_hz
+    }
 }
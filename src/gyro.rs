@@ -4,13 +4,161 @@ use embedded_hal::digital::v2::OutputPin;
 use crate::{accumulator::AccumulatorF32, serial_println, millis};
 
 pub const SAMPLE_PERIOD: u16 = 2;
-const CALIBRATION_SAMPLE_TIME: u32 = 5_000;
-const DEGREE_PER_SECOND_PER_LSB: f32 = 1.0 / 80.0;
+pub(crate) const CALIBRATION_SAMPLE_TIME: u32 = 5_000;
+pub(crate) const DEGREE_PER_SECOND_PER_LSB: f32 = 1.0 / 80.0;
+
+// ADXRS450 register addresses.
+pub(crate) const ADDR_RATE: u8 = 0x00;
+pub(crate) const ADDR_TEMP: u8 = 0x02;
+const ADDR_FAULT1: u8 = 0x0A;
+const ADDR_FAULT2: u8 = 0x0C;
+
+// ADXRS450 raw temperature-to-Celsius conversion, per the datasheet.
+const TEMP_OFFSET_LSB: f32 = 31.0;
+const TEMP_LSB_PER_DEGREE: f32 = 5.0;
+const TEMP_ZERO_C_OFFSET: f32 = 25.0;
+
+// Status field (bits 27:26 of the response word).
+const STATUS_NORMAL: u32 = 0b01;
+const STATUS_FAULT: u32 = 0b10;
+
+// Re-initialize the sensor after this many consecutive faulted/corrupted
+// samples, mirroring the deliberate-error-then-recover pattern used by
+// PX4's IMU backends. Shared with `GyroArray`'s per-channel recovery.
+pub(crate) const CONSECUTIVE_FAULT_THRESHOLD: u32 = 10;
+
+// Health score bounds, shared with `GyroArray`'s fault-weighted voting.
+pub(crate) const MAX_HEALTH: u8 = 100;
+const HEALTH_PENALTY: u8 = 10;
+const HEALTH_RECOVERY: u8 = 1;
+
+// Re-read the temperature register once every this many successful
+// `update()` samples outside calibration instead of on every sample: board temperature drifts on a multi-second
+// thermal time constant, so there's nothing to gain from paying for a
+// second SPI transaction every ~2ms alongside the rate read, and doing so
+// just drags down the observed-rate filter in `AccumulatorF32`. Shared with
+// `GyroArray`'s own throttled temperature read.
+pub(crate) const TEMP_READ_INTERVAL: u32 = 250;
+
+/**
+ * Decoded latched fault flags, read back from the FAULT1/FAULT2 registers
+ * once the status field reports a fault condition.
+ */
+pub struct FaultFlags {
+    pub continuous_self_test: bool,
+    pub pll: bool,
+    pub quadrature: bool,
+    pub nvm_checksum: bool,
+}
+
+/**
+ * Validation failure for a register read/write, decoded from the
+ * response word's parity bits and status field.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GyroError {
+    /// P0/P1 odd-parity check failed; the word was corrupted in flight.
+    Parity,
+    /// Status field reported a fault condition (see `FaultFlags`).
+    Fault,
+    /// Status field reported neither "normal" nor "fault".
+    Status,
+}
+
+/**
+ * Common interface for anything that can report an angular rate and
+ * integrated angle, implemented by both a single `ADXRS450` and a
+ * fault-weighted `GyroArray` of several of them.
+ */
+pub trait RateGyro {
+    fn update(&mut self);
+    fn get_rate(&self) -> f32;
+    fn get_angle(&self) -> f32;
+    /// Health score in `0..=MAX_HEALTH`; lower means less trustworthy.
+    fn health(&self) -> u8;
+}
+
+/**
+ * Set the P0 (full-word) and P1 (upper-16-bit) odd-parity bits on a
+ * command word before it's transmitted. P1 lives at bit 25: the address
+ * field (bits 24:17) never reaches it since `addr` is a `u8`, and the
+ * value field (bits 16:1) is entirely below it, so neither can collide
+ * with or be corrupted by it the way bit 16 would. Both bits start out
+ * clear in `word` and are computed in order, since P1 is itself covered
+ * by P0.
+ */
+pub(crate) fn with_parity(word: u32) -> u32 {
+    let mut word = word;
+
+    if (word >> 16).count_ones() % 2 == 0 {
+        word |= 1 << 25; // P1
+    }
+
+    if word.count_ones() % 2 == 0 {
+        word |= 1; // P0
+    }
+
+    word
+}
+
+/**
+ * Verify the odd-parity bits ADXRS450 appends to every response: P0
+ * covers the full 32-bit word, P1 covers just the upper 16 bits
+ * (31:16). Returns false on a parity mismatch, which indicates the
+ * transfer corrupted the word in flight.
+ */
+fn parity_ok(response: u32) -> bool {
+    let p0_ok = (response.count_ones() % 2) == 1;
+
+    let upper = response >> 16;
+    let p1_ok = (upper.count_ones() % 2) == 1;
+
+    p0_ok && p1_ok
+}
+
+/**
+ * Validate a response word's parity and status field, and extract its
+ * 16-bit data field.
+ */
+pub(crate) fn validate_response(response: u32) -> Result<u16, GyroError> {
+    if !parity_ok(response) {
+        return Err(GyroError::Parity);
+    }
+
+    let status = (response >> 26) & 0b11;
+
+    if status == STATUS_FAULT {
+        return Err(GyroError::Fault);
+    }
+
+    if status != STATUS_NORMAL {
+        return Err(GyroError::Status);
+    }
+
+    // Extract the 16 data bits and shift them down to a u16
+    Ok(((response & 0b00000011_11111111_11111100_00000000) >> 10) as u16)
+}
+
+/// Convert a raw ADXRS450 temperature register reading to degrees Celsius,
+/// per the datasheet. Shared with `GyroArray`'s own per-channel read.
+pub(crate) fn raw_temp_to_celsius(raw: u16) -> f32 {
+    (raw as f32 - TEMP_OFFSET_LSB) / TEMP_LSB_PER_DEGREE + TEMP_ZERO_C_OFFSET
+}
 
 pub struct ADXRS450 {
     spi: Spi,
     cs: ChipSelectPin<PB2>,
     acc: AccumulatorF32,
+    base_center: f32,
+    calib_temp: f32,
+    temp_coefficient: f32,
+    last_temp: f32,
+    error_count: u32,
+    consecutive_faults: u32,
+    in_calibration: bool,
+    health: u8,
+    samples_since_temp_read: u32,
+    consecutive_fault_threshold: u32,
 }
 
 impl ADXRS450 {
@@ -19,6 +167,18 @@ impl ADXRS450 {
             spi,
             cs,
             acc: AccumulatorF32::new(),
+            base_center: 0.0,
+            calib_temp: 0.0,
+            temp_coefficient: 0.0,
+            last_temp: 0.0,
+            error_count: 0,
+            consecutive_faults: 0,
+            in_calibration: false,
+            health: MAX_HEALTH,
+            // Force a temperature read on the very first `update()` call
+            // rather than waiting a full `TEMP_READ_INTERVAL` on a stale 0.0.
+            samples_since_temp_read: TEMP_READ_INTERVAL,
+            consecutive_fault_threshold: CONSECUTIVE_FAULT_THRESHOLD,
         };
 
         gyro.calibrate();
@@ -26,12 +186,12 @@ impl ADXRS450 {
         gyro
     }
 
-    fn read_sensor_data(&mut self) -> u16 {
+    fn transfer_command(&mut self, mut command: [u8; 4]) -> u32 {
         // Begin Write
 
         self.cs.set_low().unwrap();
 
-        self.spi.transfer(&mut [0x20, 0x00, 0x00, 0x00]).unwrap();
+        self.spi.transfer(&mut command).unwrap();
 
         self.cs.set_high().unwrap();
 
@@ -50,28 +210,164 @@ impl ADXRS450 {
 
         // End Read
 
-        let response = u32::from_be_bytes(data);
+        u32::from_be_bytes(data)
+    }
 
-        // Check if status bits are not 0b01 (Error Returned)
-        if ((response >> 24 & 0b0000_1100) >> 2) != 0b01 {
-            serial_println!("[?] read_sensor_data() produced an error! ");
-            return 0;
+    /// Read a register's 16-bit data field over SPI.
+    pub fn read_register(&mut self, addr: u8) -> Result<u16, GyroError> {
+        let command = with_parity(0x80000000 | (addr as u32) << 17);
+        let response = self.transfer_command(command.to_be_bytes());
+
+        validate_response(response)
+    }
+
+    /// Write a 16-bit value to a register over SPI.
+    pub fn write_register(&mut self, addr: u8, value: u16) -> Result<u16, GyroError> {
+        let command = with_parity(0x40000000 | (addr as u32) << 17 | (value as u32) << 1);
+        let response = self.transfer_command(command.to_be_bytes());
+
+        validate_response(response)
+    }
+
+    /**
+     * Issue the follow-up register reads for the latched fault flags once
+     * the status field has reported a fault condition.
+     */
+    fn decode_faults(&mut self) -> FaultFlags {
+        let fault1 = self.read_register(ADDR_FAULT1).unwrap_or(0);
+        let fault2 = self.read_register(ADDR_FAULT2).unwrap_or(0);
+
+        FaultFlags {
+            continuous_self_test: fault1 & (1 << 0) != 0,
+            pll: fault1 & (1 << 1) != 0,
+            quadrature: fault2 & (1 << 0) != 0,
+            nvm_checksum: fault2 & (1 << 1) != 0,
         }
+    }
 
-        // TODO: Check response parity bits
+    /**
+     * Read the rate register. Returns `None` (instead of feeding a
+     * corrupted sample into the accumulator) on a parity mismatch or a
+     * decoded fault.
+     */
+    fn read_sensor_data(&mut self) -> Option<u16> {
+        match self.read_register(ADDR_RATE) {
+            Ok(rate) => Some(rate),
+            Err(GyroError::Parity) => {
+                serial_println!("[!] read_sensor_data() parity check failed!");
+                None
+            }
+            Err(GyroError::Fault) => {
+                let faults = self.decode_faults();
+                serial_println!(
+                    "[!] Gyro fault - CST: {:?} PLL: {:?} QUAD: {:?} NVM: {:?}",
+                    faults.continuous_self_test,
+                    faults.pll,
+                    faults.quadrature,
+                    faults.nvm_checksum
+                );
+                None
+            }
+            Err(GyroError::Status) => {
+                serial_println!("[?] read_sensor_data() produced an error! ");
+                None
+            }
+        }
+    }
+
+    /**
+     * Read the ADXRS450's on-chip temperature register and return the
+     * result in degrees Celsius.
+     */
+    pub fn read_temperature(&mut self) -> f32 {
+        let raw = self.read_register(ADDR_TEMP).unwrap_or(0);
 
-        // Extract the 16 data bits and shift them down to a u16
-        ((response & 0b00000011_11111111_11111100_00000000) >> 10) as u16
+        raw_temp_to_celsius(raw)
     }
 
     pub fn update(&mut self) {
-        let rate = self.read_sensor_data();
+        let rate = match self.read_sensor_data() {
+            Some(rate) => rate,
+            None => {
+                self.error_count += 1;
+                self.consecutive_faults += 1;
+                self.health = self.health.saturating_sub(HEALTH_PENALTY);
+
+                if !self.in_calibration && self.consecutive_faults >= self.consecutive_fault_threshold
+                {
+                    serial_println!("[!] Too many consecutive faults, re-initializing gyro...");
+                    self.reinitialize();
+                }
+
+                return;
+            }
+        };
+        self.consecutive_faults = 0;
+        self.health = (self.health + HEALTH_RECOVERY).min(MAX_HEALTH);
         let rate = i16::from_be_bytes(rate.to_be_bytes());
 
+        // `calibrate()` zeroes the integrated center itself while it
+        // measures a fresh bias; recomputing the temp-compensated center
+        // here on every sample would stomp that zero and bias the
+        // in-progress measurement by whatever `base_center` was before
+        // this calibration started.
+        if !self.in_calibration {
+            self.samples_since_temp_read += 1;
+            if self.samples_since_temp_read >= TEMP_READ_INTERVAL {
+                self.samples_since_temp_read = 0;
+                self.last_temp = self.read_temperature();
+            }
+
+            let center =
+                self.base_center + self.temp_coefficient * (self.last_temp - self.calib_temp);
+            self.acc.set_integrated_center(center);
+        }
+
         self.acc.add_data(rate as f32);
     }
 
+    /**
+     * Override the number of consecutive faulted/corrupted samples that
+     * triggers a full re-initialization; defaults to
+     * `CONSECUTIVE_FAULT_THRESHOLD`.
+     */
+    pub fn set_consecutive_fault_threshold(&mut self, threshold: u32) {
+        self.consecutive_fault_threshold = threshold;
+    }
+
+    /**
+     * Full sensor re-initialization after too many consecutive faults:
+     * re-run calibration from scratch, as PX4's IMU backends do on
+     * persistent self-test failure.
+     */
+    pub fn reinitialize(&mut self) {
+        self.consecutive_faults = 0;
+        self.health = MAX_HEALTH;
+        self.calibrate();
+    }
+
+    pub fn get_error_count(&self) -> u32 {
+        self.error_count
+    }
+
+    /**
+     * Set the measured zero-rate bias drift per °C, in the units of the
+     * calibrated integrated center it is added to: the raw bias in LSB
+     * times the sample period in seconds (e.g. 0.1 °/s per °C at
+     * 80 LSB/(°/s) and a 2ms period is 0.016). Defaults to 0.0 (no
+     * compensation) until the user provides a real measured slope.
+     */
+    pub fn set_temp_coefficient(&mut self, coefficient: f32) {
+        self.temp_coefficient = coefficient;
+    }
+
+    pub fn get_temperature(&self) -> f32 {
+        self.last_temp
+    }
+
     pub fn calibrate(&mut self) {
+        self.in_calibration = true;
+
         serial_println!("[+] Starting calibration...");
 
         arduino_hal::delay_ms(100);
@@ -95,10 +391,14 @@ impl ADXRS450 {
 
         let average = self.acc.get_integrated_average();
 
+        self.base_center = average;
+        self.calib_temp = self.read_temperature();
         self.acc.set_integrated_center(average);
         self.acc.reset();
 
         serial_println!("[+] Finished calibration!");
+
+        self.in_calibration = false;
     }
 
     pub fn reset(&mut self) {
@@ -112,4 +412,26 @@ impl ADXRS450 {
     pub fn get_rate(&self) -> f32 {
         self.acc.get_last_value() * DEGREE_PER_SECOND_PER_LSB
     }
+
+    pub fn get_effective_rate_hz(&self) -> f32 {
+        self.acc.get_effective_rate_hz()
+    }
+}
+
+impl RateGyro for ADXRS450 {
+    fn update(&mut self) {
+        ADXRS450::update(self)
+    }
+
+    fn get_rate(&self) -> f32 {
+        ADXRS450::get_rate(self)
+    }
+
+    fn get_angle(&self) -> f32 {
+        ADXRS450::get_angle(self)
+    }
+
+    fn health(&self) -> u8 {
+        self.health
+    }
 }
@@ -0,0 +1,385 @@
+use arduino_hal::prelude::_embedded_hal_blocking_spi_Transfer;
+use arduino_hal::Spi;
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::accumulator::AccumulatorF32;
+use crate::gyro::{
+    raw_temp_to_celsius, validate_response, with_parity, GyroError, RateGyro, ADDR_RATE,
+    ADDR_TEMP, CALIBRATION_SAMPLE_TIME, CONSECUTIVE_FAULT_THRESHOLD, DEGREE_PER_SECOND_PER_LSB,
+    MAX_HEALTH, SAMPLE_PERIOD, TEMP_READ_INTERVAL,
+};
+use crate::millis;
+use crate::serial_println;
+
+const HEALTH_PENALTY: u8 = 10;
+const HEALTH_RECOVERY: u8 = 1;
+// A sensor below this health is excluded from the fused rate until it
+// recovers, mirroring PX4's priority/voting scheme for redundant IMUs.
+const MIN_HEALTH_FOR_CONSENSUS: u8 = 50;
+// Disagreement from the array median beyond this (in raw LSB counts, same
+// units `AccumulatorF32` integrates) marks a sensor as an outlier for this
+// tick; ~5 deg/s at the ADXRS450's 80 LSB/(deg/s) scale factor.
+const DEFAULT_OUTLIER_THRESHOLD: f32 = 400.0;
+
+struct Channel<CS> {
+    cs: CS,
+    acc: AccumulatorF32,
+    bias: f32,
+    health: u8,
+    last_rate: f32,
+    last_disagreement: f32,
+    consecutive_faults: u32,
+}
+
+/**
+ * Drives several ADXRS450s sharing one SPI bus, each on its own
+ * chip-select line, and fuses their rates into a single `RateGyro` with
+ * PX4-style fault-weighted voting: every sensor carries a health score
+ * that's penalized on a read error or on disagreeing with the array
+ * median, and the fused rate is the health-weighted average of sensors
+ * still in consensus. A sensor that drops below `MIN_HEALTH_FOR_CONSENSUS`
+ * is excluded until its health recovers, either passively (a run of
+ * successful reads) or, after `CONSECUTIVE_FAULT_THRESHOLD` faults in a
+ * row, by re-calibrating that channel outright - the per-channel analog
+ * of `ADXRS450::reinitialize`.
+ */
+pub struct GyroArray<CS, const N: usize> {
+    spi: Spi,
+    channels: [Channel<CS>; N],
+    fused: AccumulatorF32,
+    primary: usize,
+    outlier_threshold: f32,
+    error_count: u32,
+    temperature: f32,
+    samples_since_temp_read: u32,
+}
+
+impl<CS, const N: usize> GyroArray<CS, N>
+where
+    CS: OutputPin,
+    CS::Error: core::fmt::Debug,
+{
+    pub fn new(spi: Spi, cs_pins: [CS; N]) -> Self {
+        let mut array = GyroArray {
+            spi,
+            channels: cs_pins.map(|cs| Channel {
+                cs,
+                acc: AccumulatorF32::new(),
+                bias: 0.0,
+                health: MAX_HEALTH,
+                last_rate: 0.0,
+                last_disagreement: 0.0,
+                consecutive_faults: 0,
+            }),
+            fused: AccumulatorF32::new(),
+            primary: 0,
+            outlier_threshold: DEFAULT_OUTLIER_THRESHOLD,
+            error_count: 0,
+            temperature: 0.0,
+            // Force a temperature read on the very first `update()` call
+            // rather than waiting a full `TEMP_READ_INTERVAL` on a stale 0.0.
+            samples_since_temp_read: TEMP_READ_INTERVAL,
+        };
+
+        array.calibrate();
+
+        array
+    }
+
+    pub fn set_outlier_threshold(&mut self, threshold: f32) {
+        self.outlier_threshold = threshold;
+    }
+
+    fn transfer_channel(&mut self, index: usize, addr: u8) -> u32 {
+        let mut command = with_parity(0x80000000 | (addr as u32) << 17).to_be_bytes();
+
+        self.channels[index].cs.set_low().unwrap();
+        self.spi.transfer(&mut command).unwrap();
+        self.channels[index].cs.set_high().unwrap();
+
+        arduino_hal::delay_us(500);
+
+        let mut data = [0; 4];
+        self.channels[index].cs.set_low().unwrap();
+        self.spi.transfer(&mut data).unwrap();
+        self.channels[index].cs.set_high().unwrap();
+
+        u32::from_be_bytes(data)
+    }
+
+    fn read_channel_rate(&mut self, index: usize) -> Result<f32, GyroError> {
+        let response = self.transfer_channel(index, ADDR_RATE);
+        let raw = validate_response(response)?;
+
+        Ok(i16::from_be_bytes(raw.to_be_bytes()) as f32)
+    }
+
+    /// Read one channel's on-chip temperature register, in degrees Celsius.
+    fn read_channel_temperature(&mut self, index: usize) -> Option<f32> {
+        let response = self.transfer_channel(index, ADDR_TEMP);
+        let raw = validate_response(response).ok()?;
+
+        Some(raw_temp_to_celsius(raw))
+    }
+
+    pub fn calibrate(&mut self) {
+        serial_println!("[+] Calibrating gyro array...");
+
+        arduino_hal::delay_ms(100);
+
+        for channel in self.channels.iter_mut() {
+            channel.bias = 0.0;
+            channel.acc.set_integrated_center(0.0);
+            channel.acc.reset();
+        }
+
+        let start_time = millis::get_millis();
+
+        while millis::get_millis() - start_time <= CALIBRATION_SAMPLE_TIME {
+            for i in 0..N {
+                if let Ok(rate) = self.read_channel_rate(i) {
+                    self.channels[i].acc.add_data(rate);
+                }
+            }
+
+            arduino_hal::delay_ms(SAMPLE_PERIOD);
+        }
+
+        for channel in self.channels.iter_mut() {
+            channel.bias = channel.acc.get_integrated_average();
+            channel.acc.set_integrated_center(channel.bias);
+            channel.acc.reset();
+        }
+
+        serial_println!("[+] Gyro array calibration complete");
+    }
+
+    /**
+     * Re-run one channel's bias calibration after too many consecutive
+     * faults, mirroring `ADXRS450::reinitialize` - a channel that's
+     * genuinely stuck faulting would otherwise never recover once its
+     * health crosses `MIN_HEALTH_FOR_CONSENSUS`, since health only
+     * recovers passively on a successful read.
+     */
+    fn recalibrate_channel(&mut self, index: usize) {
+        serial_println!(
+            "[!] Channel {:?} has too many consecutive faults, re-calibrating...",
+            index
+        );
+
+        self.channels[index].consecutive_faults = 0;
+        self.channels[index].health = MAX_HEALTH;
+        self.channels[index].bias = 0.0;
+        // `AccumulatorF32::reset()` doesn't clear `integrated_center`, so
+        // without this the re-measurement loop below would still subtract
+        // the stale pre-fault bias from every sample, and the average at
+        // the end would come out as `true_bias - old_bias` instead of
+        // `true_bias`.
+        self.channels[index].acc.set_integrated_center(0.0);
+        self.channels[index].acc.reset();
+
+        let start_time = millis::get_millis();
+
+        while millis::get_millis() - start_time <= CALIBRATION_SAMPLE_TIME {
+            if let Ok(rate) = self.read_channel_rate(index) {
+                self.channels[index].acc.add_data(rate);
+            }
+
+            arduino_hal::delay_ms(SAMPLE_PERIOD);
+        }
+
+        self.channels[index].bias = self.channels[index].acc.get_integrated_average();
+        self.channels[index]
+            .acc
+            .set_integrated_center(self.channels[index].bias);
+        self.channels[index].acc.reset();
+    }
+
+    /// Median of the rates flagged `true` in `ok`; 0.0 if none are.
+    fn median(rates: &[f32; N], ok: &[bool; N]) -> f32 {
+        let mut sorted = [0.0f32; N];
+        let mut count = 0;
+
+        for i in 0..N {
+            if ok[i] {
+                sorted[count] = rates[i];
+                count += 1;
+            }
+        }
+
+        // Insertion sort - N is small (a handful of redundant sensors).
+        for i in 1..count {
+            let mut j = i;
+            while j > 0 && sorted[j - 1] > sorted[j] {
+                sorted.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+
+        if count == 0 {
+            return 0.0;
+        }
+
+        if count % 2 == 1 {
+            sorted[count / 2]
+        } else {
+            (sorted[count / 2 - 1] + sorted[count / 2]) / 2.0
+        }
+    }
+
+    pub fn get_primary(&self) -> usize {
+        self.primary
+    }
+
+    pub fn get_channel_health(&self, index: usize) -> u8 {
+        self.channels[index].health
+    }
+
+    pub fn get_channel_disagreement(&self, index: usize) -> f32 {
+        self.channels[index].last_disagreement * DEGREE_PER_SECOND_PER_LSB
+    }
+
+    /// Print each sensor's health and disagreement from the array median.
+    pub fn log_status(&self) {
+        for (i, channel) in self.channels.iter().enumerate() {
+            serial_println!(
+                "[?] Gyro[{:?}] Health: {:?} | Disagreement: {:?}°/s | Primary: {:?}\r",
+                i,
+                channel.health,
+                (channel.last_disagreement * DEGREE_PER_SECOND_PER_LSB) as i32,
+                i == self.primary
+            );
+        }
+    }
+
+    /// Reset the fused integral and every channel's calibration accumulator.
+    pub fn reset(&mut self) {
+        self.fused.reset();
+
+        for channel in self.channels.iter_mut() {
+            channel.acc.reset();
+        }
+    }
+
+    pub fn get_effective_rate_hz(&self) -> f32 {
+        self.fused.get_effective_rate_hz()
+    }
+
+    /// Total count of channel read failures across the whole array's
+    /// lifetime, the array-level analog of `ADXRS450::get_error_count`.
+    pub fn get_error_count(&self) -> u32 {
+        self.error_count
+    }
+
+    /**
+     * Temperature reading from the primary channel, throttled the same way
+     * `ADXRS450::update` throttles its own temperature read - it's only
+     * used for the text/binary telemetry surface, not for per-channel
+     * compensation.
+     */
+    pub fn get_temperature(&self) -> f32 {
+        self.temperature
+    }
+}
+
+impl<CS, const N: usize> RateGyro for GyroArray<CS, N>
+where
+    CS: OutputPin,
+    CS::Error: core::fmt::Debug,
+{
+    fn update(&mut self) {
+        let mut rates = [0.0f32; N];
+        let mut ok = [false; N];
+
+        for i in 0..N {
+            match self.read_channel_rate(i) {
+                Ok(raw_rate) => {
+                    let rate = raw_rate - self.channels[i].bias;
+                    rates[i] = rate;
+                    ok[i] = true;
+                    self.channels[i].last_rate = rate;
+                    self.channels[i].consecutive_faults = 0;
+                    self.channels[i].health =
+                        (self.channels[i].health + HEALTH_RECOVERY).min(MAX_HEALTH);
+                }
+                Err(_) => {
+                    self.channels[i].health = self.channels[i].health.saturating_sub(HEALTH_PENALTY);
+                    self.channels[i].consecutive_faults += 1;
+                    self.error_count += 1;
+
+                    if self.channels[i].consecutive_faults >= CONSECUTIVE_FAULT_THRESHOLD {
+                        self.recalibrate_channel(i);
+                    }
+                }
+            }
+        }
+
+        self.samples_since_temp_read += 1;
+        if self.samples_since_temp_read >= TEMP_READ_INTERVAL {
+            self.samples_since_temp_read = 0;
+            if let Some(temperature) = self.read_channel_temperature(self.primary) {
+                self.temperature = temperature;
+            }
+        }
+
+        let median = Self::median(&rates, &ok);
+
+        let mut weighted_sum = 0.0;
+        let mut weight_total = 0.0;
+        let mut best_health = 0u8;
+
+        for i in 0..N {
+            if !ok[i] {
+                continue;
+            }
+
+            let disagreement = (rates[i] - median).abs();
+            self.channels[i].last_disagreement = disagreement;
+
+            if disagreement > self.outlier_threshold {
+                self.channels[i].health = self.channels[i].health.saturating_sub(HEALTH_PENALTY);
+            }
+
+            let in_consensus =
+                self.channels[i].health >= MIN_HEALTH_FOR_CONSENSUS && disagreement <= self.outlier_threshold;
+
+            if in_consensus {
+                let weight = self.channels[i].health as f32;
+                weighted_sum += rates[i] * weight;
+                weight_total += weight;
+            }
+
+            if self.channels[i].health > best_health {
+                best_health = self.channels[i].health;
+                self.primary = i;
+            }
+        }
+
+        let fused_rate = if weight_total > 0.0 {
+            weighted_sum / weight_total
+        } else {
+            median
+        };
+
+        // Skip the sample entirely on a total dropout (no channel validated
+        // this tick), matching `ADXRS450::update`'s early return on its own
+        // fault path - otherwise `median`'s "no valid channels" fallback of
+        // 0.0 would get fed straight into the fused accumulator as a
+        // fabricated "stationary" reading.
+        if ok.iter().any(|&valid| valid) {
+            self.fused.add_data(fused_rate);
+        }
+    }
+
+    fn get_rate(&self) -> f32 {
+        self.fused.get_last_value() * DEGREE_PER_SECOND_PER_LSB
+    }
+
+    fn get_angle(&self) -> f32 {
+        self.fused.get_integrated_value() * DEGREE_PER_SECOND_PER_LSB
+    }
+
+    fn health(&self) -> u8 {
+        self.channels[self.primary].health
+    }
+}
@@ -2,25 +2,30 @@
 #![no_main]
 #![feature(abi_avr_interrupt)]
 
+mod accumulator;
+mod gyro;
+mod gyro_array;
 mod millis;
+mod telemetry;
 
-use arduino_hal::hal::port::PB2;
 use arduino_hal::prelude::*;
-use arduino_hal::spi::ChipSelectPin;
 use arduino_hal::spi::DataOrder;
 use arduino_hal::spi::SerialClockRate;
-use arduino_hal::Spi;
-use embedded_hal::digital::v2::OutputPin;
 use embedded_hal::spi::MODE_0;
+use gyro::RateGyro;
+use gyro_array::GyroArray;
 use panic_halt as _;
+use telemetry::{Command, CommandDecoder, ReportMode, ReportPacket};
 
-const SAMPLE_PERIOD: u16 = 2;
-const CALIBRATION_SAMPLE_TIME: u32 = 5_000;
-const DEGREE_PER_SECOND_PER_LSB: f32 = 1.0 / 80.0;
+/// Number of ADXRS450s sharing the SPI bus, fused by `GyroArray` - a single
+/// gyro is a single point of failure, so the board carries this many on
+/// separate chip-select lines.
+const GYRO_COUNT: usize = 3;
 
 pub mod serial {
     use avr_device::interrupt::Mutex;
     use core::cell::RefCell;
+    use embedded_hal::serial::{Read, Write};
 
     pub type Usart = arduino_hal::hal::usart::Usart0<arduino_hal::DefaultClock>;
     pub static GLOBAL_SERIAL: Mutex<RefCell<Option<Usart>>> = Mutex::new(RefCell::new(None));
@@ -31,6 +36,27 @@ pub mod serial {
         })
     }
 
+    /// Non-blocking single-byte read from the host, for the in-band command channel.
+    pub fn try_read_byte() -> Option<u8> {
+        avr_device::interrupt::free(|cs| {
+            let mut serial = GLOBAL_SERIAL.borrow(cs).borrow_mut();
+            serial.as_mut().and_then(|serial| serial.read().ok())
+        })
+    }
+
+    /// Blocking raw byte write, for binary telemetry frames.
+    pub fn write_bytes(bytes: &[u8]) {
+        avr_device::interrupt::free(|cs| {
+            let mut serial = GLOBAL_SERIAL.borrow(cs).borrow_mut();
+            if let Some(serial) = serial.as_mut() {
+                for &byte in bytes {
+                    nb::block!(serial.write(byte)).ok();
+                }
+                nb::block!(serial.flush()).ok();
+            }
+        })
+    }
+
     #[macro_export]
     macro_rules! serial_println {
         ($($arg:tt)*) => {
@@ -56,8 +82,8 @@ fn setup() -> ! {
     let mosi = pins.d11.into_output();
     let miso = pins.d12.into_pull_up_input();
     let cs0 = pins.d10.into_output_high();
-
-    let reset_pin = pins.d5.into_pull_up_input();
+    let cs1 = pins.d9.into_output_high();
+    let cs2 = pins.d8.into_output_high();
 
     // Set up serial interface for text output
     let serial = arduino_hal::default_serial!(dp, pins, 57600);
@@ -75,8 +101,11 @@ fn setup() -> ! {
 
     serial_println!("[+] Creating SPI interface");
 
-    // Create SPI interface.
-    let (spi, cs) = arduino_hal::Spi::new(
+    // Create SPI interface. `cs0` doubles as the hardware SS pin the AVR's
+    // SPI peripheral requires to run in master mode; `cs1`/`cs2` are plain
+    // GPIO outputs toggled by `GyroArray` itself to address the other two
+    // sensors sharing this same bus.
+    let (spi, cs0) = arduino_hal::Spi::new(
         dp.SPI,
         sclk,
         mosi,
@@ -89,199 +118,60 @@ fn setup() -> ! {
         },
     );
 
-    serial_println!("[+] Creating gyro instance");
+    serial_println!("[+] Creating gyro array");
 
-    // Create gyro instance
-    let mut gyro = ADXRS450::new(spi, cs);
+    // Create the fused gyro array
+    let mut gyro: GyroArray<_, GYRO_COUNT> =
+        GyroArray::new(spi, [cs0.downgrade(), cs1.downgrade(), cs2.downgrade()]);
 
     serial_println!("[+] Entering main loop");
 
+    let mut decoder = CommandDecoder::new();
+
+    #[cfg(feature = "binary-telemetry")]
+    let mut report_mode = ReportMode::Binary;
+    #[cfg(not(feature = "binary-telemetry"))]
+    let mut report_mode = ReportMode::Text;
+
     loop {
-        // If reset switch is pulled low (closed), reset the gyro
-        if reset_pin.is_low() {
-            gyro.reset()
+        // Drain at most one host command per iteration; zero/reset,
+        // recalibrate, and mode/rate switches all arrive in-band now.
+        if let Some(command) = decoder.poll() {
+            match command {
+                Command::Zero => gyro.reset(),
+                Command::Recalibrate => gyro.calibrate(),
+                Command::SetMode(mode) => report_mode = mode,
+                Command::SetReportPeriod(period_ms) => telemetry::set_report_period_ms(period_ms),
+            }
         }
 
         // Update the gyro accumulator
         gyro.update();
 
-        // Print out gyro state
-        serial_println!(
-            "[?] Gyro Rate: {:?}°/s | Gyro Angle: {:?}°\r",
-            gyro.get_rate() as i32,
-            gyro.get_angle() as i32
-        );
-
-        // Wait before continuing (trying to get 500Hz)
-        arduino_hal::delay_ms(SAMPLE_PERIOD);
-    }
-}
-
-struct ADXRS450 {
-    spi: Spi,
-    cs: ChipSelectPin<PB2>,
-    acc: AccumulatorF32,
-}
-
-impl ADXRS450 {
-    fn new(spi: Spi, cs: ChipSelectPin<PB2>) -> Self {
-        let mut gyro = ADXRS450 {
-            spi,
-            cs,
-            acc: AccumulatorF32::new(),
-        };
-
-        gyro.calibrate();
-
-        gyro
-    }
-
-    fn read_sensor_data(&mut self) -> u16 {
-        // Begin Write
-
-        self.cs.set_low().unwrap();
-
-        self.spi.transfer(&mut [0x20, 0x00, 0x00, 0x00]).unwrap();
-
-        self.cs.set_high().unwrap();
-
-        // End Write
-
-        arduino_hal::delay_us(500);
-
-        // Begin Read
-
-        self.cs.set_low().unwrap();
-
-        let mut data = [0; 4];
-        self.spi.transfer(&mut data).unwrap();
-
-        self.cs.set_high().unwrap();
-
-        // End Read
-
-        let response = u32::from_be_bytes(data);
-
-        // Check if status bits are not 0b01 (Error Returned)
-        if ((response >> 24 & 0b0000_1100) >> 2) != 0b01 {
-            serial_println!("[?] read_sensor_data() produced an error! ");
-            return 0;
-        }
-
-        // TODO: Check response parity bits
-
-        // Extract the 16 data bits and shift them down to a u16
-        ((response & 0b00000011_11111111_11111100_00000000) >> 10) as u16
-    }
-
-    pub fn update(&mut self) {
-        let rate = self.read_sensor_data();
-        let rate = i16::from_be_bytes(rate.to_be_bytes());
-
-        self.acc.add_data(rate as f32);
-    }
-
-    pub fn calibrate(&mut self) {
-        serial_println!("[+] Starting calibration...");
-
-        arduino_hal::delay_ms(100);
-
-        self.acc.set_integrated_center(0.0);
-        self.acc.reset();
-
-        let start_time = millis::get_millis();
-
-        loop {
-            if millis::get_millis() - start_time > CALIBRATION_SAMPLE_TIME {
-                break;
+        match report_mode {
+            ReportMode::Text => {
+                serial_println!(
+                    "[?] Gyro Rate: {:?}°/s | Gyro Angle: {:?}° | Rate: {:?}Hz | Temp: {:?}°C | Errors: {:?}\r",
+                    gyro.get_rate() as i32,
+                    gyro.get_angle() as i32,
+                    gyro.get_effective_rate_hz() as i32,
+                    gyro.get_temperature() as i32,
+                    gyro.get_error_count()
+                );
+            }
+            ReportMode::Binary => {
+                let packet = ReportPacket {
+                    rate: gyro.get_rate(),
+                    angle: gyro.get_angle(),
+                    temperature: gyro.get_temperature(),
+                    error_count: gyro.get_error_count() as u16,
+                };
+
+                serial::write_bytes(&packet.encode());
             }
-
-            // Update the gyro accumulator
-            self.update();
-
-            // Wait before continuing (trying to get 500Hz)
-            arduino_hal::delay_ms(SAMPLE_PERIOD);
-        }
-
-        let average = self.acc.get_integrated_average();
-
-        self.acc.set_integrated_center(average);
-        self.acc.reset();
-
-        serial_println!("[+] Finished calibration!");
-    }
-
-    pub fn reset(&mut self) {
-        self.acc.reset()
-    }
-
-    pub fn get_angle(&self) -> f32 {
-        self.acc.get_integrated_value() * DEGREE_PER_SECOND_PER_LSB
-    }
-
-    pub fn get_rate(&self) -> f32 {
-        self.acc.get_last_value() * DEGREE_PER_SECOND_PER_LSB
-    }
-}
-
-struct AccumulatorF32 {
-    accumulated: f32,
-    samples: u32,
-    last_value: f32,
-    last_time: u32,
-    integrated_center: f32,
-}
-
-impl AccumulatorF32 {
-    pub fn new() -> Self {
-        AccumulatorF32::with_default(0.0)
-    }
-
-    pub fn with_default(default: f32) -> Self {
-        AccumulatorF32 {
-            accumulated: default,
-            samples: 0,
-            last_value: 0.0,
-            last_time: millis::get_millis(),
-            integrated_center: 0.0,
         }
-    }
-
-    /**
-     * Integrate the added data using the trapezoidal method
-     */
-    pub fn add_data(&mut self, value: f32) {
-        let time = millis::get_millis();
-
-        let delta_time_ms = time - self.last_time;
-        let area =
-            delta_time_ms as f32 * 1e-3 * (self.last_value + value) / 2.0 - self.integrated_center;
-
-        self.accumulated += area;
-        self.last_value = value;
-        self.last_time = time;
-        self.samples += 1;
-    }
-
-    pub fn get_integrated_value(&self) -> f32 {
-        self.accumulated
-    }
-
-    pub fn get_last_value(&self) -> f32 {
-        self.last_value
-    }
-
-    pub fn reset(&mut self) {
-        self.accumulated = 0.0;
-        self.last_value = 0.0;
-        self.last_time = millis::get_millis();
-    }
-
-    pub fn set_integrated_center(&mut self, center: f32) {
-        self.integrated_center = center
-    }
 
-    pub fn get_integrated_average(&self) -> f32 {
-        self.accumulated / self.samples as f32
+        // Wait before continuing (trying to get 500Hz by default)
+        arduino_hal::delay_ms(telemetry::get_report_period_ms());
     }
 }
@@ -0,0 +1,56 @@
+use avr_device::interrupt::Mutex;
+use core::cell;
+
+// Timer0 is configured for CTC mode, prescaled by 64, counting up to
+// TIMER_COUNTS before firing the compare-match interrupt used to drive
+// `millis()`. At 16 MHz this works out to one interrupt per millisecond.
+const PRESCALER: u32 = 64;
+const TIMER_COUNTS: u32 = 250;
+
+const MILLIS_INCREMENT: u32 = PRESCALER * TIMER_COUNTS / 16000;
+
+// Microseconds covered by each Timer0 tick within the current millisecond
+// (TIMER_COUNTS ticks at PRESCALER/16MHz = 4us/tick).
+const MICROS_PER_TICK: u32 = PRESCALER * 1_000_000 / 16_000_000;
+
+static MILLIS_COUNTER: Mutex<cell::Cell<u32>> = Mutex::new(cell::Cell::new(0));
+
+pub fn millis_init(tc0: arduino_hal::pac::TC0) {
+    tc0.tccr0a.write(|w| w.wgm0().ctc());
+    tc0.ocr0a.write(|w| w.bits(TIMER_COUNTS as u8));
+    tc0.tccr0b.write(|w| w.cs0().prescale_64());
+    tc0.timsk0.write(|w| w.ocie0a().set_bit());
+
+    avr_device::interrupt::free(|cs| {
+        MILLIS_COUNTER.borrow(cs).set(0);
+    });
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn TIMER0_COMPA() {
+    avr_device::interrupt::free(|cs| {
+        let counter_cell = MILLIS_COUNTER.borrow(cs);
+        let counter = counter_cell.get();
+        counter_cell.set(counter + MILLIS_INCREMENT);
+    })
+}
+
+pub fn get_millis() -> u32 {
+    avr_device::interrupt::free(|cs| MILLIS_COUNTER.borrow(cs).get())
+}
+
+/**
+ * Microsecond-resolution clock, derived from the same Timer0 used by
+ * `get_millis()`. Reads the free-running tick counter alongside the
+ * millisecond counter so callers get sub-millisecond precision without a
+ * dedicated timer.
+ */
+pub fn get_micros() -> u32 {
+    avr_device::interrupt::free(|cs| {
+        let ms = MILLIS_COUNTER.borrow(cs).get();
+        // SAFETY: reading TCNT0 is a single volatile load; we're inside a
+        // critical section so it can't race the compare-match handler.
+        let tcnt0 = unsafe { (*arduino_hal::pac::TC0::ptr()).tcnt0.read().bits() };
+        ms * 1000 + tcnt0 as u32 * MICROS_PER_TICK
+    })
+}
@@ -0,0 +1,125 @@
+use avr_device::interrupt::Mutex;
+use core::cell::Cell;
+
+use crate::gyro;
+use crate::serial;
+
+pub const SYNC_BYTE: u8 = 0xAA;
+const REPORT_MESSAGE_ID: u8 = 0x01;
+
+/// Total encoded length of a `ReportPacket`: sync + id + 3 f32s + u16 + checksum.
+pub const REPORT_PACKET_LEN: usize = 17;
+
+pub mod command {
+    pub const SET_MODE: u8 = 0x00;
+    pub const ZERO: u8 = 0x01;
+    pub const RECALIBRATE: u8 = 0x02;
+    pub const SET_REPORT_PERIOD: u8 = 0x03;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ReportMode {
+    Text,
+    Binary,
+}
+
+static REPORT_PERIOD_MS: Mutex<Cell<u16>> = Mutex::new(Cell::new(gyro::SAMPLE_PERIOD));
+
+pub fn get_report_period_ms() -> u16 {
+    avr_device::interrupt::free(|cs| REPORT_PERIOD_MS.borrow(cs).get())
+}
+
+pub fn set_report_period_ms(period_ms: u16) {
+    avr_device::interrupt::free(|cs| REPORT_PERIOD_MS.borrow(cs).set(period_ms));
+}
+
+/**
+ * Fixed-layout binary telemetry frame: sync byte, message id, little-endian
+ * rate/angle/temperature, little-endian error count, and a checksum - the
+ * XOR of every preceding byte, so a receiver can validate a frame by
+ * XOR-folding the whole thing down to zero. Modeled on the Telemetrix
+ * request/report wire format.
+ */
+pub struct ReportPacket {
+    pub rate: f32,
+    pub angle: f32,
+    pub temperature: f32,
+    pub error_count: u16,
+}
+
+impl ReportPacket {
+    pub fn encode(&self) -> [u8; REPORT_PACKET_LEN] {
+        let mut frame = [0u8; REPORT_PACKET_LEN];
+
+        frame[0] = SYNC_BYTE;
+        frame[1] = REPORT_MESSAGE_ID;
+        frame[2..6].copy_from_slice(&self.rate.to_le_bytes());
+        frame[6..10].copy_from_slice(&self.angle.to_le_bytes());
+        frame[10..14].copy_from_slice(&self.temperature.to_le_bytes());
+        frame[14..16].copy_from_slice(&self.error_count.to_le_bytes());
+
+        frame[16] = frame[..16].iter().fold(0u8, |checksum, byte| checksum ^ byte);
+
+        frame
+    }
+}
+
+pub enum Command {
+    SetMode(ReportMode),
+    Zero,
+    Recalibrate,
+    SetReportPeriod(u16),
+}
+
+enum DecoderState {
+    Idle,
+    AwaitingData(u8),
+}
+
+/**
+ * Minimal stateful decoder for host commands arriving on the serial RX
+ * line, replacing the old reset-only GPIO pin with an in-band control
+ * channel. `poll()` consumes at most one byte per call so a command whose
+ * opcode takes a data byte can straddle main-loop iterations without
+ * losing sync.
+ */
+pub struct CommandDecoder {
+    state: DecoderState,
+}
+
+impl CommandDecoder {
+    pub const fn new() -> Self {
+        CommandDecoder {
+            state: DecoderState::Idle,
+        }
+    }
+
+    pub fn poll(&mut self) -> Option<Command> {
+        let byte = serial::try_read_byte()?;
+
+        match self.state {
+            DecoderState::Idle => match byte {
+                command::ZERO => Some(Command::Zero),
+                command::RECALIBRATE => Some(Command::Recalibrate),
+                command::SET_MODE | command::SET_REPORT_PERIOD => {
+                    self.state = DecoderState::AwaitingData(byte);
+                    None
+                }
+                _ => None,
+            },
+            DecoderState::AwaitingData(opcode) => {
+                self.state = DecoderState::Idle;
+
+                match opcode {
+                    command::SET_MODE => Some(Command::SetMode(if byte == 0 {
+                        ReportMode::Text
+                    } else {
+                        ReportMode::Binary
+                    })),
+                    command::SET_REPORT_PERIOD => Some(Command::SetReportPeriod(byte as u16)),
+                    _ => None,
+                }
+            }
+        }
+    }
+}